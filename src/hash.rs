@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, trace};
+
+use crate::error::{Error, ResultExt};
+use crate::walk;
+
+/// On-disk cache of input digests, keyed by output path.
+///
+/// Stored as `.mk/hashes.json` relative to the working directory. Each entry
+/// records the combined SHA-256 of the inputs that produced that output, so a
+/// rebuild is triggered whenever the inputs' content (rather than their mtime)
+/// changes.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HashCache {
+    #[serde(flatten)]
+    digests: BTreeMap<String, String>,
+}
+
+const CACHE_PATH: &str = ".mk/hashes.json";
+
+impl HashCache {
+    /// Load the cache, returning an empty cache if it does not yet exist.
+    pub fn load() -> Result<HashCache, Error> {
+        match std::fs::read(CACHE_PATH) {
+            Ok(data) => {
+                serde_json::from_slice(&data).map_err_path_context(PathBuf::from(CACHE_PATH))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashCache::default()),
+            Err(e) => Err(Error::IO(PathBuf::from(CACHE_PATH), e)),
+        }
+    }
+
+    /// The cached digest for `output`, if any.
+    pub fn get(&self, output: &str) -> Option<&str> {
+        self.digests.get(output).map(String::as_str)
+    }
+
+    /// Record `digest` as the current input digest for `output`.
+    pub fn set(&mut self, output: &str, digest: String) {
+        self.digests.insert(output.to_string(), digest);
+    }
+
+    /// Drop the cached digest for `output`, returning whether an entry existed.
+    pub fn remove(&mut self, output: &str) -> bool {
+        self.digests.remove(output).is_some()
+    }
+
+    /// Persist the cache to `.mk/hashes.json`, creating the `.mk` directory if
+    /// necessary.
+    ///
+    /// The data is written to a per-process temp file and renamed into place so
+    /// a concurrent reader (e.g. another `mk --hash -j` run) never observes a
+    /// half-written file.
+    pub fn store(&self) -> Result<(), Error> {
+        if let Some(parent) = Path::new(CACHE_PATH).parent() {
+            std::fs::create_dir_all(parent).map_err_path_context(parent)?;
+        }
+        let data = serde_json::to_vec_pretty(self).map_err_path_context(PathBuf::from(CACHE_PATH))?;
+        let tmp = PathBuf::from(format!("{CACHE_PATH}.{}.tmp", std::process::id()));
+        std::fs::write(&tmp, data).map_err_path_context(tmp.clone())?;
+        std::fs::rename(&tmp, CACHE_PATH).map_err_path_context(PathBuf::from(CACHE_PATH))
+    }
+}
+
+/// Compute the combined SHA-256 digest of `inputs`.
+///
+/// Each regular file contributes its own SHA-256 and relative path; directory
+/// inputs are folded over every regular file they contain. The per-file
+/// digests are concatenated in sorted path order so the result is independent
+/// of filesystem iteration order. Directory inputs are scanned with the same
+/// gitignore/`.git` filtering as the mtime path (unless `no_ignore` is set) so
+/// both modes agree on which files count. An unreadable or removed input is
+/// reported as an error, which callers treat as "changed".
+pub fn digest_inputs(inputs: &[String], no_ignore: bool) -> Result<String, Error> {
+    let mut entries: BTreeMap<String, String> = BTreeMap::new();
+    for input in inputs {
+        walk::walk_files(input, no_ignore, |path, _| {
+            let digest = hash_file(path)?;
+            trace!("hashed {} -> {}", path.display(), digest);
+            entries.insert(path.to_string_lossy().into_owned(), digest);
+            Ok(())
+        })?;
+    }
+
+    let mut hasher = Sha256::new();
+    for (path, digest) in &entries {
+        hasher.update(path.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(digest.as_bytes());
+        hasher.update(b"\n");
+    }
+    let combined = hex(&hasher.finalize());
+    debug!("combined input digest is {}", combined);
+    Ok(combined)
+}
+
+/// Stream `path` through SHA-256 in fixed-size chunks, returning a hex digest.
+fn hash_file(path: &Path) -> Result<String, Error> {
+    let mut file = std::fs::File::open(path).map_err_path_context(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = file.read(&mut buf).map_err_path_context(path)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(s, "{b:02x}");
+    }
+    s
+}
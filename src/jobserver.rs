@@ -0,0 +1,89 @@
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use jobserver::{Acquired, Client};
+use tracing::debug;
+
+use crate::error::Error;
+
+/// A resolved jobserver to coordinate parallelism through.
+///
+/// Like every GNU make instance, each `mk` owns one free "implicit" slot — the
+/// token the parent already reserved to run this recipe — which its single
+/// command runs on without touching the pool. A pooled token is acquired only
+/// for a 2nd+ concurrent job. This holds whether the pool was created here or
+/// joined from `MAKEFLAGS`, so recursive `mk` invocations can't deadlock by
+/// each demanding a pooled token from a pool that reserved none for them.
+pub struct Jobserver {
+    client: Client,
+    implicit_used: AtomicBool,
+}
+
+/// A held job slot, released when dropped — either the implicit slot (no pooled
+/// token) or a token borrowed from the pool.
+pub enum Token<'a> {
+    Implicit(&'a AtomicBool),
+    Pooled(#[allow(dead_code)] Acquired),
+}
+
+impl Drop for Token<'_> {
+    fn drop(&mut self) {
+        // A `Pooled` token releases itself when its `Acquired` drops; the
+        // implicit slot just needs its flag cleared for the next command.
+        if let Token::Implicit(flag) = self {
+            flag.store(false, Ordering::Release);
+        }
+    }
+}
+
+impl Jobserver {
+    /// Export the pool to `command` so grandchild `mk` invocations join it.
+    pub fn configure(&self, command: &mut Command) {
+        self.client.configure(command);
+    }
+
+    /// Acquire a slot for one command, held until the returned token drops. The
+    /// first concurrent command runs on the free implicit slot; any further
+    /// one blocks on a pooled token.
+    pub fn acquire(&self) -> Result<Token<'_>, Error> {
+        if self
+            .implicit_used
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            Ok(Token::Implicit(&self.implicit_used))
+        } else {
+            Ok(Token::Pooled(self.client.acquire()?))
+        }
+    }
+}
+
+/// Resolve the jobserver to coordinate parallelism through, if any.
+///
+/// If `MAKEFLAGS` already advertises a jobserver (because `mk` was invoked by
+/// `make` or by an outer `mk -j`), join that pool so nested invocations share
+/// a single token budget. Otherwise, a top-level `-j N` creates a fresh pool
+/// pre-filled with `N - 1` tokens — the remaining slot is the one this process
+/// implicitly owns. Without either, returns `None` and commands run
+/// unthrottled as before.
+pub fn resolve(jobs: Option<usize>) -> Result<Option<Jobserver>, Error> {
+    // Safety: called once, before any threads or child processes are spawned.
+    if let Some(client) = unsafe { Client::from_env() } {
+        debug!("joining existing jobserver from MAKEFLAGS");
+        return Ok(Some(Jobserver {
+            client,
+            implicit_used: AtomicBool::new(false),
+        }));
+    }
+    match jobs {
+        Some(n) => {
+            debug!("creating jobserver with {} slots", n);
+            let client = Client::new(n.saturating_sub(1))?;
+            Ok(Some(Jobserver {
+                client,
+                implicit_used: AtomicBool::new(false),
+            }))
+        }
+        None => Ok(None),
+    }
+}
@@ -1,6 +1,10 @@
 mod error;
 mod file;
+mod hash;
+mod jobserver;
 mod target;
+mod walk;
+mod watch;
 
 use crate::error::Error;
 use clap::Parser;
@@ -23,6 +27,39 @@ struct Args {
     chdir: String,
     #[arg(long, default_value = "error", help = "Set log level")]
     log_level: Level,
+    #[arg(
+        long,
+        help = "Decide rebuilds by input content digest instead of mtime, caching digests in .mk/hashes.json"
+    )]
+    hash: bool,
+    #[arg(
+        long,
+        short = 'j',
+        value_name = "N",
+        help = "Limit parallelism to N concurrent commands across cooperating `mk` invocations (GNU make jobserver)"
+    )]
+    jobs: Option<usize>,
+    #[arg(
+        long,
+        help = "After the initial build, keep running and rebuild whenever inputs change"
+    )]
+    watch: bool,
+    #[arg(
+        long,
+        help = "Scan every file in directory inputs, including .git and .gitignore'd paths"
+    )]
+    no_ignore: bool,
+    #[arg(
+        long,
+        short = 'q',
+        help = "Capture command output and only print it if the command fails"
+    )]
+    quiet: bool,
+    #[arg(
+        long,
+        help = "On command failure, delete newly-created outputs and restore pre-existing ones' mtimes"
+    )]
+    delete_on_error: bool,
     #[arg(
         trailing_var_arg = true,
         allow_hyphen_values = true,
@@ -61,16 +98,50 @@ fn main() {
         exit(0);
     }
 
-    let target = Target::parse(args.args).unwrap_or_else(|e| {
+    // Keep a copy of the raw arguments so `--watch` can re-parse (and so
+    // re-compute staleness) on each change.
+    let raw_args = args.args.clone();
+
+    let jobserver = jobserver::resolve(args.jobs).unwrap_or_else(|e| {
+        eprintln!("mk: error: {}", e);
+        exit(1);
+    });
+
+    let target = Target::parse_with(args.args, args.hash, args.no_ignore).unwrap_or_else(|e| {
         eprintln!("mk: error: {}", e);
         exit(1);
     });
-    if !target.should_run_command() {
+
+    // Run the initial build. In watch mode a failure is reported but does not
+    // abort; the loop waits for the next change.
+    let result = if target.should_run_command() {
+        target.run_command(jobserver.as_ref(), args.quiet, args.delete_on_error)
+    } else {
         debug!("Nothing to do.");
+        Ok(())
+    };
+
+    if args.watch {
+        if let Err(e) = &result {
+            eprintln!("mk: error: {}", e);
+        }
+        let inputs = target.inputs().to_vec();
+        if let Err(e) = watch::watch(
+            raw_args,
+            args.hash,
+            args.no_ignore,
+            args.quiet,
+            args.delete_on_error,
+            jobserver.as_ref(),
+            &inputs,
+        ) {
+            eprintln!("mk: error: {}", e);
+            exit(1);
+        }
         exit(0);
     }
 
-    match target.run_command(args.chdir.as_str()) {
+    match result {
         Ok(()) => exit(0),
         Err(Error::CommandFailed(code)) => exit(code),
         Err(e) => {
@@ -91,6 +91,64 @@ fn test_mk_input_is_dir() {
     assert!(before < after);
 }
 
+#[test]
+fn test_mk_delete_on_error_removes_partial_output() {
+    let tmpdir = tempdir().unwrap();
+    touch(&tmpdir, "input");
+    // The command creates the output and then fails; --delete-on-error must
+    // remove the partially-written output rather than leave it behind.
+    assert_mk_fails(
+        &tmpdir,
+        &[
+            "--delete-on-error",
+            "output",
+            ":",
+            "input",
+            "--",
+            "touch output && false",
+        ],
+    );
+    assert_not_exists(&tmpdir, "output");
+}
+
+#[test]
+fn test_mk_hash_skips_identical_rewrite() {
+    let tmpdir = tempdir().unwrap();
+    write(&tmpdir, "input", "hello");
+    assert_mk_ok(
+        &tmpdir,
+        &["--hash", "output", ":", "input", "--", "touch", "output"],
+    );
+    assert_exists(&tmpdir, "output");
+    let before = file_time(&tmpdir, "output");
+    // Rewriting the input with identical content must not trigger a rebuild.
+    write(&tmpdir, "input", "hello");
+    assert_mk_ok(
+        &tmpdir,
+        &["--hash", "output", ":", "input", "--", "touch", "output"],
+    );
+    let after = file_time(&tmpdir, "output");
+    assert_eq!(before, after);
+}
+
+#[test]
+fn test_mk_hash_rebuilds_on_content_change() {
+    let tmpdir = tempdir().unwrap();
+    write(&tmpdir, "input", "hello");
+    assert_mk_ok(
+        &tmpdir,
+        &["--hash", "output", ":", "input", "--", "touch", "output"],
+    );
+    let before = file_time(&tmpdir, "output");
+    write(&tmpdir, "input", "goodbye");
+    assert_mk_ok(
+        &tmpdir,
+        &["--hash", "output", ":", "input", "--", "touch", "output"],
+    );
+    let after = file_time(&tmpdir, "output");
+    assert!(before < after);
+}
+
 #[track_caller]
 fn assert_mk_ok(tmpdir: &TempDir, args: &[&str]) {
     let mut cmd = Command::cargo_bin("mk").unwrap();
@@ -144,6 +202,11 @@ fn touch(tmpdir: &TempDir, path: &str) {
         .unwrap();
 }
 
+#[track_caller]
+fn write(tmpdir: &TempDir, path: &str, contents: &str) {
+    std::fs::write(tmpdir.path().join(path), contents).unwrap();
+}
+
 fn file_time(tmpdir: &TempDir, path: &str) -> std::time::SystemTime {
     let path = tmpdir.path().join(path);
     std::fs::metadata(path).unwrap().modified().unwrap()
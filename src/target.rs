@@ -1,21 +1,36 @@
-use std::io::ErrorKind;
+use std::io::{BufRead, BufReader, ErrorKind, Read};
 use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
 use std::time::SystemTime;
 
 use crate::error::{Error, ResultExt};
+use crate::hash::{self, HashCache};
+use crate::jobserver;
+use crate::walk;
 use crate::File;
 use tracing::{debug, info, trace};
-use walkdir::WalkDir;
 
 pub struct Target {
     outputs: Vec<String>,
+    inputs: Vec<String>,
     command: Vec<String>,
     pub needs_rebuild: bool,
+    /// Input digest to persist once the command succeeds, when running in
+    /// `--hash` mode.
+    hash_digest: Option<String>,
 }
 
 impl Target {
     // Parse the arguments into a Target struct.
     pub fn parse(args: Vec<String>) -> Result<Target, Error> {
+        Self::parse_with(args, false, false)
+    }
+
+    // Parse the arguments into a Target struct, deciding staleness by content
+    // digest rather than mtime when `hash` is set, and honoring `no_ignore`.
+    pub fn parse_with(args: Vec<String>, hash: bool, no_ignore: bool) -> Result<Target, Error> {
         // Option<File>
         let mut newest_output = File::default();
         let mut outputs = Vec::new();
@@ -36,9 +51,13 @@ impl Target {
             return Err(Error::MissingOutputs);
         }
 
+        if hash {
+            return Self::parse_hashed(outputs, inputs, command, no_ignore);
+        }
+
         // Find latest output
         for output in outputs.iter() {
-            let newest = match find_newest(output) {
+            let newest = match find_newest(output, no_ignore) {
                 Err(e) if e.is_not_found() => {
                     info!(r#"output "{}" does not exist, rebuilding"#, output);
                     needs_rebuild = true;
@@ -54,7 +73,7 @@ impl Target {
         }
 
         for input in inputs.iter() {
-            let newest = match find_newest(input) {
+            let newest = match find_newest(input, no_ignore) {
                 Ok(n) => n,
                 Err(e) if e.is_not_found() => {
                     return Err(Error::MissingInput(input.clone()));
@@ -68,8 +87,10 @@ impl Target {
                 );
                 return Ok(Target {
                     outputs,
+                    inputs: inputs.clone(),
                     command,
                     needs_rebuild: true,
+                    hash_digest: None,
                 });
             } else {
                 trace!(
@@ -90,8 +111,70 @@ impl Target {
         }
         Ok(Target {
             outputs,
+            inputs,
+            command,
+            needs_rebuild,
+            hash_digest: None,
+        })
+    }
+
+    /// Decide staleness by content digest rather than mtime.
+    ///
+    /// The combined SHA-256 of all inputs is compared against the digest cached
+    /// in `.mk/hashes.json` for each output; a rebuild is triggered when any
+    /// output has no cached entry, has a differing digest, or does not yet
+    /// exist. An unreadable or removed input is treated as "changed".
+    fn parse_hashed(
+        outputs: Vec<String>,
+        inputs: Vec<String>,
+        command: Vec<String>,
+        no_ignore: bool,
+    ) -> Result<Target, Error> {
+        let cache = HashCache::load()?;
+        let digest = match hash::digest_inputs(&inputs, no_ignore) {
+            Ok(d) => Some(d),
+            Err(e) => {
+                info!("input unreadable, rebuilding: {}", e);
+                None
+            }
+        };
+
+        let mut needs_rebuild = false;
+        let mut any_output_exists = false;
+        for output in outputs.iter() {
+            match std::fs::metadata(output) {
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    info!(r#"output "{}" does not exist, rebuilding"#, output);
+                    needs_rebuild = true;
+                }
+                Err(e) => return Err(Error::IO(PathBuf::from(output), e)),
+                Ok(_) => {
+                    any_output_exists = true;
+                    match (cache.get(output), digest.as_deref()) {
+                        (Some(cached), Some(current)) if cached == current => {
+                            trace!(r#"input digest for "{}" is unchanged"#, output);
+                        }
+                        _ => {
+                            info!(r#"input digest for "{}" changed, rebuilding"#, output);
+                            needs_rebuild = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Mirror the mtime path: a missing output with no command to produce it
+        // is an error, not a silent "nothing to do".
+        if !any_output_exists && command.is_empty() {
+            return Err(Error::MissingOutput(outputs[0].clone()));
+        }
+
+        Ok(Target {
+            outputs,
+            inputs,
             command,
             needs_rebuild,
+            hash_digest: digest,
         })
     }
 
@@ -100,9 +183,30 @@ impl Target {
         self.needs_rebuild && !self.command.is_empty()
     }
 
+    /// The resolved input paths, for registering with a filesystem watcher.
+    pub fn inputs(&self) -> &[String] {
+        &self.inputs
+    }
+
     // Run the command and verify that all outputs exist.
     // Will not display the command if it is prefixed with `@`.
-    pub fn run_command(&self) -> Result<(), Error> {
+    //
+    // When a jobserver is present the command holds one token for the duration
+    // of its run, so concurrent `mk` invocations respect a shared `-j` ceiling.
+    //
+    // When output is being captured (see below) the child's stdout and stderr
+    // are re-emitted line-by-line tagged with the output name (`[out] …`) so
+    // interleaved rules stay attributable. In `quiet` mode captured output is
+    // buffered and flushed to stderr only if the command fails, keeping clean
+    // builds silent. Capture is only engaged under `--quiet` or when a
+    // jobserver is active (commands may run in parallel); otherwise the child
+    // inherits the terminal so TTY-detecting commands behave as before.
+    pub fn run_command(
+        &self,
+        jobserver: Option<&jobserver::Jobserver>,
+        quiet: bool,
+        delete_on_error: bool,
+    ) -> Result<(), Error> {
         let mut shell_command = if self.command.len() > 1 {
             shell_words::join(&self.command)
         } else {
@@ -114,12 +218,79 @@ impl Target {
         } else {
             println!("{}", &shell_command);
         }
-        let status = std::process::Command::new("bash")
-            .args(vec!["-c", shell_command.as_str()])
-            .status()?
-            .code()
-            .unwrap_or(-1);
+        // Capture and prefix output only when the user asked for quiet mode or
+        // a jobserver means commands may run concurrently; otherwise let the
+        // child inherit the terminal so interactive/TTY-detecting commands
+        // (colors, progress bars, prompts) keep working.
+        let capture = quiet || jobserver.is_some();
+        let mut command = std::process::Command::new("bash");
+        command.args(vec!["-c", shell_command.as_str()]);
+        if capture {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+        // Acquire a token before spawning and export the pool to the child so
+        // grandchild `mk` invocations join the same budget. The token is held
+        // until the command exits and released when `_token` drops, even on the
+        // error paths below.
+        let _token = match jobserver {
+            Some(js) => {
+                js.configure(&mut command);
+                Some(js.acquire()?)
+            }
+            None => None,
+        };
+        // Snapshot each output's pre-run mtime (if it exists) so a failed run
+        // can be rolled back rather than leaving a newer-but-broken file. In
+        // hash mode rollback additionally invalidates the cached digest (see
+        // below).
+        let snapshot: Vec<(&String, Option<SystemTime>)> =
+            if delete_on_error {
+                self.outputs
+                    .iter()
+                    .map(|o| (o, std::fs::metadata(o).and_then(|m| m.modified()).ok()))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+
+        let mut child = command.spawn()?;
+
+        // Read both pipes on their own threads so neither can deadlock by
+        // filling its buffer while we wait on the other.
+        let buffer = Arc::new(Mutex::new(Vec::<String>::new()));
+        let pumps = if capture {
+            let label = self.outputs.first().cloned().unwrap_or_default();
+            let stdout = child.stdout.take().expect("stdout piped");
+            let stderr = child.stderr.take().expect("stderr piped");
+            let out_pump = pump(stdout, label.clone(), quiet, false, Arc::clone(&buffer));
+            let err_pump = pump(stderr, label, quiet, true, Arc::clone(&buffer));
+            Some((out_pump, err_pump))
+        } else {
+            None
+        };
+
+        let status = child.wait()?.code().unwrap_or(-1);
+        if let Some((out_pump, err_pump)) = pumps {
+            let _ = out_pump.join();
+            let _ = err_pump.join();
+        }
+
         if status != 0 {
+            // On failure, release the buffered output so the error is visible.
+            if quiet {
+                for line in buffer.lock().unwrap().iter() {
+                    eprintln!("{line}");
+                }
+            }
+            if delete_on_error {
+                if self.hash_digest.is_some() {
+                    // Hash mode ignores mtime, so a restored timestamp wouldn't
+                    // force a rebuild; drop the cached digest so the next run
+                    // sees the (possibly corrupt) output as stale and rebuilds.
+                    invalidate_cache(&self.outputs);
+                }
+                roll_back_outputs(&snapshot);
+            }
             return Err(Error::CommandFailed(status));
         }
         for output in self.outputs.iter() {
@@ -131,30 +302,112 @@ impl Target {
                 Err(e) => return Err(e.into()),
             };
         }
+        // In `--hash` mode, record the input digest only once the command has
+        // succeeded and every output has been verified to exist.
+        if let Some(digest) = &self.hash_digest {
+            let mut cache = HashCache::load()?;
+            for output in self.outputs.iter() {
+                cache.set(output, digest.clone());
+            }
+            cache.store()?;
+        }
         Ok(())
     }
 }
 
-/// Recurse into directories to find the newest file.
+/// Drop the cached input digest for each failed output so `--hash` mode treats
+/// a half-written output as stale on the next run. Errors are ignored — a
+/// missing or unreadable cache already means "rebuild".
+fn invalidate_cache(outputs: &[String]) {
+    if let Ok(mut cache) = HashCache::load() {
+        let mut changed = false;
+        for output in outputs {
+            changed |= cache.remove(output);
+        }
+        if changed {
+            let _ = cache.store();
+        }
+    }
+}
+
+/// Undo a failed build's effect on its outputs (GNU make's `.DELETE_ON_ERROR`).
 ///
-/// Returns the newest file's modified time and its path.
-fn find_newest(path: &str) -> Result<File, Error> {
+/// Outputs that did not exist before the run are removed so a partial write
+/// doesn't masquerade as up to date; outputs that did exist have their original
+/// mtime restored so a failed rebuild doesn't leave a newer-but-broken file.
+/// Errors are ignored — there is nothing useful to do if cleanup itself fails.
+fn roll_back_outputs(snapshot: &[(&String, Option<SystemTime>)]) {
+    for (output, before) in snapshot {
+        match before {
+            Some(modified) => {
+                if let Ok(file) = std::fs::OpenOptions::new().write(true).open(output) {
+                    let _ = file.set_modified(*modified);
+                }
+            }
+            None => {
+                if std::fs::remove_file(output).is_err() {
+                    let _ = std::fs::remove_dir_all(output);
+                }
+            }
+        }
+    }
+}
+
+/// Read `reader` line by line on a new thread, tagging each line with the
+/// target's output name. In quiet mode lines are buffered into `buffer` for a
+/// deferred flush; otherwise they are written straight to stdout (or stderr,
+/// when `is_err`).
+fn pump<R: Read + Send + 'static>(
+    reader: R,
+    label: String,
+    quiet: bool,
+    is_err: bool,
+    buffer: Arc<Mutex<Vec<String>>>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut bytes = Vec::new();
+        loop {
+            bytes.clear();
+            // Read a line's raw bytes so non-UTF-8 output doesn't abort the
+            // pump (and, under --quiet, truncate the deferred error flush).
+            match reader.read_until(b'\n', &mut bytes) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            while matches!(bytes.last(), Some(b'\n') | Some(b'\r')) {
+                bytes.pop();
+            }
+            let tagged = format!("[{label}] {}", String::from_utf8_lossy(&bytes));
+            if quiet {
+                buffer.lock().unwrap().push(tagged);
+            } else if is_err {
+                eprintln!("{tagged}");
+            } else {
+                println!("{tagged}");
+            }
+        }
+    })
+}
+
+/// Recurse into directories to find the newest file, skipping ignored paths
+/// unless `no_ignore` is set (see [`walk::walk_files`]).
+fn find_newest(path: &str, no_ignore: bool) -> Result<File, Error> {
     let mut newest = File {
         path: PathBuf::from(path),
         modified: SystemTime::UNIX_EPOCH,
     };
-    for entry in WalkDir::new(path).follow_links(true) {
-        let entry = entry.map_err_path_context(path)?;
-        let path = entry.path().to_path_buf();
-        let metadata = entry.metadata().map_err_path_context(path.clone())?;
-        if !metadata.is_file() {
-            continue;
-        }
-        let modified = metadata.modified().map_err_path_context(path.clone())?;
-
+    walk::walk_files(path, no_ignore, |entry_path, metadata| {
+        let modified = metadata
+            .modified()
+            .map_err_path_context(entry_path.to_path_buf())?;
         if modified > newest.modified {
-            newest = File { path, modified };
+            newest = File {
+                path: entry_path.to_path_buf(),
+                modified,
+            };
         }
-    }
+        Ok(())
+    })?;
     Ok(newest)
 }
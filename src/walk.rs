@@ -0,0 +1,91 @@
+use std::fs::Metadata;
+use std::path::Path;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use walkdir::WalkDir;
+
+use crate::error::{Error, ResultExt};
+
+/// Recurse into `path`, invoking `visit` for each regular file found.
+///
+/// Unless `no_ignore` is set, files matched by a `.gitignore` (accumulated
+/// per-directory as the walk descends, honoring `!` negation and `dir/`
+/// directory-only patterns) and the `.git` directory itself are skipped, so
+/// build artifacts and VCS internals are excluded from both the mtime and the
+/// content-digest comparisons. The per-directory ignore stack is built lazily
+/// so deep trees don't reparse ancestor ignore files repeatedly.
+pub fn walk_files(
+    path: &str,
+    no_ignore: bool,
+    mut visit: impl FnMut(&Path, &Metadata) -> Result<(), Error>,
+) -> Result<(), Error> {
+    // Stack of (directory depth, matcher) built lazily as we descend.
+    let mut ignores: Vec<(usize, Gitignore)> = Vec::new();
+    let mut it = WalkDir::new(path).follow_links(true).into_iter();
+    while let Some(entry) = it.next() {
+        let entry = entry.map_err_path_context(path)?;
+        let depth = entry.depth();
+        let entry_path = entry.path().to_path_buf();
+        let metadata = entry.metadata().map_err_path_context(entry_path.clone())?;
+        let is_dir = metadata.is_dir();
+
+        if !no_ignore {
+            // Discard matchers for directories we've already walked out of.
+            while ignores.last().is_some_and(|(d, _)| *d >= depth) {
+                ignores.pop();
+            }
+            // Always skip the VCS metadata directory.
+            if is_dir && entry.file_name() == ".git" {
+                it.skip_current_dir();
+                continue;
+            }
+            if is_ignored(&ignores, &entry_path, is_dir) {
+                if is_dir {
+                    it.skip_current_dir();
+                }
+                continue;
+            }
+            if is_dir {
+                if let Some(gitignore) = load_gitignore(&entry_path)? {
+                    ignores.push((depth, gitignore));
+                }
+            }
+        }
+
+        if !metadata.is_file() {
+            continue;
+        }
+        visit(&entry_path, &metadata)?;
+    }
+    Ok(())
+}
+
+/// Build the `.gitignore` matcher rooted at `dir`, or `None` if it has none.
+fn load_gitignore(dir: &Path) -> Result<Option<Gitignore>, Error> {
+    let file = dir.join(".gitignore");
+    if !file.is_file() {
+        return Ok(None);
+    }
+    let mut builder = GitignoreBuilder::new(dir);
+    if let Some(e) = builder.add(&file) {
+        return Err(Error::IO(file, std::io::Error::other(e.to_string())));
+    }
+    let gitignore = builder
+        .build()
+        .map_err(|e| Error::IO(dir.to_path_buf(), std::io::Error::other(e.to_string())))?;
+    Ok(Some(gitignore))
+}
+
+/// Test `path` against the accumulated ignore stack, deepest matcher first so
+/// nearer `.gitignore` rules (including negations) win.
+fn is_ignored(ignores: &[(usize, Gitignore)], path: &Path, is_dir: bool) -> bool {
+    for (_, gitignore) in ignores.iter().rev() {
+        match gitignore.matched(path, is_dir) {
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+            Match::None => continue,
+        }
+    }
+    false
+}
@@ -0,0 +1,104 @@
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::Duration;
+
+use notify::{RecursiveMode, Watcher};
+use tracing::{debug, info};
+
+use crate::error::Error;
+use crate::jobserver;
+use crate::target::Target;
+
+/// Window to coalesce bursts of filesystem events (e.g. an editor's
+/// write-rename-chmod save sequence) into a single rebuild.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Re-run the rule whenever its inputs change, until interrupted.
+///
+/// The initial build has already happened by the time this is called. Each
+/// input path is registered recursively with a filesystem watcher, so new
+/// files created under directory inputs also trigger rebuilds. Bursts of
+/// events are debounced over a short window; a command failure is reported and
+/// the loop keeps waiting for the next change. `SIGINT` exits cleanly.
+pub fn watch(
+    args: Vec<String>,
+    hash: bool,
+    no_ignore: bool,
+    quiet: bool,
+    delete_on_error: bool,
+    jobserver: Option<&jobserver::Jobserver>,
+    inputs: &[String],
+) -> Result<(), Error> {
+    if inputs.is_empty() {
+        info!("nothing to watch; no inputs were given");
+        return Ok(());
+    }
+
+    ctrlc::set_handler(|| exit(0)).map_err(io_error)?;
+
+    let (tx, rx) = channel();
+    let mut watcher = notify::recommended_watcher(move |res| {
+        let _ = tx.send(res);
+    })
+    .map_err(io_error)?;
+    for input in inputs {
+        watcher
+            .watch(Path::new(input), RecursiveMode::Recursive)
+            .map_err(|e| Error::IO(PathBuf::from(input), io(e)))?;
+    }
+    info!("watching {} input(s) for changes", inputs.len());
+
+    loop {
+        // Block until the first event of a batch arrives.
+        if rx.recv().is_err() {
+            break;
+        }
+        // Drain subsequent events until the tree settles for DEBOUNCE.
+        loop {
+            match rx.recv_timeout(DEBOUNCE) {
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+        debug!("inputs settled, re-evaluating");
+        rebuild(&args, hash, no_ignore, quiet, delete_on_error, jobserver);
+    }
+    Ok(())
+}
+
+/// Re-parse and re-run the target, reporting the outcome without tearing down
+/// the watch loop on failure.
+fn rebuild(
+    args: &[String],
+    hash: bool,
+    no_ignore: bool,
+    quiet: bool,
+    delete_on_error: bool,
+    jobserver: Option<&jobserver::Jobserver>,
+) {
+    let target = match Target::parse_with(args.to_vec(), hash, no_ignore) {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("mk: error: {}", e);
+            return;
+        }
+    };
+    if !target.should_run_command() {
+        debug!("nothing to do");
+        return;
+    }
+    match target.run_command(jobserver, quiet, delete_on_error) {
+        Ok(()) => info!("rebuild succeeded"),
+        Err(e) => eprintln!("mk: error: {}", e),
+    }
+}
+
+fn io(e: notify::Error) -> std::io::Error {
+    std::io::Error::other(e.to_string())
+}
+
+fn io_error(e: impl std::fmt::Display) -> Error {
+    Error::IO(PathBuf::new(), std::io::Error::other(e.to_string()))
+}